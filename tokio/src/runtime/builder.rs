@@ -97,7 +97,7 @@ cfg_unstable! {
     /// to configure the runtime behavior when a spawned task panics.
     ///
     /// See [`Builder::unhandled_panic`] for more details.
-    #[derive(Debug, Clone)]
+    #[derive(Clone)]
     #[non_exhaustive]
     #[repr(C)]
     pub enum UnhandledPanic {
@@ -172,8 +172,62 @@ cfg_unstable! {
     }
 }
 
+cfg_unstable! {
+    impl fmt::Debug for UnhandledPanic {
+        fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                UnhandledPanic::Ignore => fmt.debug_tuple("Ignore").finish(),
+                UnhandledPanic::ShutdownRuntime => fmt.debug_tuple("ShutdownRuntime").finish(),
+            }
+        }
+    }
+}
+
 pub(crate) type ThreadNameFn = std::sync::Arc<dyn Fn() -> String + Send + Sync + 'static>;
 
+/// Selects which scheduler a [`Builder`] (or [`RuntimeConfig`]) will build.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RuntimeFlavor {
+    /// The current-thread scheduler, see [`Builder::new_current_thread`].
+    CurrentThread,
+    /// The multi-thread scheduler, see [`Builder::new_multi_thread`].
+    #[cfg(all(feature = "rt-multi-thread", not(tokio_wasi)))]
+    MultiThread,
+}
+
+/// A plain-data description of the knobs exposed by [`Builder`].
+///
+/// `Builder` itself can't round-trip through a config file or environment
+/// variables because several of its fields are closures (`thread_name_fn`,
+/// the lifecycle callbacks). `RuntimeConfig` captures the plain-data
+/// settings listed below, and [`Builder::from_config`] applies them to a
+/// fresh `Builder`. It does not cover `start_paused`, `thread_name` or
+/// `unhandled_panic`; those, and closure-based configuration, can still be
+/// layered on top of the returned `Builder`.
+#[derive(Debug, Clone)]
+pub struct RuntimeConfig {
+    /// Which scheduler to build, see [`Builder::new_current_thread`] and
+    /// [`Builder::new_multi_thread`].
+    pub flavor: RuntimeFlavor,
+    /// See [`Builder::worker_threads`].
+    pub worker_threads: Option<usize>,
+    /// See [`Builder::max_blocking_threads`].
+    pub max_blocking_threads: Option<usize>,
+    /// See [`Builder::thread_stack_size`].
+    pub thread_stack_size: Option<usize>,
+    /// See [`Builder::thread_keep_alive`].
+    pub keep_alive: Option<Duration>,
+    /// See [`Builder::global_queue_interval`].
+    pub global_queue_interval: Option<u32>,
+    /// See [`Builder::event_interval`].
+    pub event_interval: Option<u32>,
+    /// See [`Builder::enable_io`].
+    pub enable_io: bool,
+    /// See [`Builder::enable_time`].
+    pub enable_time: bool,
+}
+
 #[repr(C)]
 pub(crate) enum Kind {
     CurrentThread,
@@ -258,6 +312,77 @@ impl Builder {
         }
     }
 
+    /// Returns a new builder configured from a plain-data [`RuntimeConfig`],
+    /// e.g. one parsed from a config file or environment variables.
+    ///
+    /// Settings that require closures — [`thread_name_fn`] and the
+    /// lifecycle callbacks — are not part of `RuntimeConfig`, nor are
+    /// `start_paused`, `thread_name` and `unhandled_panic`; these must be
+    /// applied on the returned `Builder` afterward.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tokio::runtime::{Builder, RuntimeConfig, RuntimeFlavor};
+    ///
+    /// let config = RuntimeConfig {
+    ///     flavor: RuntimeFlavor::CurrentThread,
+    ///     worker_threads: None,
+    ///     max_blocking_threads: None,
+    ///     thread_stack_size: None,
+    ///     keep_alive: None,
+    ///     global_queue_interval: None,
+    ///     event_interval: None,
+    ///     enable_io: false,
+    ///     enable_time: true,
+    /// };
+    ///
+    /// let rt = Builder::from_config(&config).build().unwrap();
+    /// ```
+    ///
+    /// [`thread_name_fn`]: Self::thread_name_fn
+    pub fn from_config(config: &RuntimeConfig) -> Builder {
+        let mut builder = match config.flavor {
+            RuntimeFlavor::CurrentThread => Builder::new_current_thread(),
+            #[cfg(all(feature = "rt-multi-thread", not(tokio_wasi)))]
+            RuntimeFlavor::MultiThread => Builder::new_multi_thread(),
+        };
+
+        if let Some(worker_threads) = config.worker_threads {
+            builder.worker_threads(worker_threads);
+        }
+        if let Some(max_blocking_threads) = config.max_blocking_threads {
+            builder.max_blocking_threads(max_blocking_threads);
+        }
+        if let Some(thread_stack_size) = config.thread_stack_size {
+            builder.thread_stack_size(thread_stack_size);
+        }
+        if let Some(keep_alive) = config.keep_alive {
+            builder.thread_keep_alive(keep_alive);
+        }
+        if let Some(global_queue_interval) = config.global_queue_interval {
+            builder.global_queue_interval(global_queue_interval);
+        }
+        if let Some(event_interval) = config.event_interval {
+            builder.event_interval(event_interval);
+        }
+
+        #[cfg(any(
+            feature = "net",
+            all(unix, feature = "process"),
+            all(unix, feature = "signal")
+        ))]
+        if config.enable_io {
+            builder.enable_io();
+        }
+        #[cfg(feature = "time")]
+        if config.enable_time {
+            builder.enable_time();
+        }
+
+        builder
+    }
+
     /// Enables both I/O and time drivers.
     ///
     /// Doing this is a shorthand for calling `enable_io` and `enable_time`
@@ -942,9 +1067,11 @@ cfg_rt_multi_thread! {
             // Create the runtime handle
             let handle = Handle { spawner };
 
-            // Spawn the thread pool workers
-            let _enter = crate::runtime::context::enter(handle.clone());
-            launch.launch();
+            // Spawn the thread pool workers. `scope` installs `handle` for
+            // the duration of `launch()` without cloning it into the owning
+            // `CONTEXT` slot, since nothing here needs the context to
+            // outlive this call.
+            crate::runtime::context::scope(&handle, || launch.launch());
 
             Ok(Runtime {
                 kind: Kind::ThreadPool(scheduler),
@@ -1,46 +1,339 @@
 //! Thread local runtime context
 use crate::runtime::{Handle, TryCurrentError};
 
-use std::cell::RefCell;
-use std::ffi::c_void;
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
 use std::thread::LocalKey;
 
 
 thread_local! {
-    static CONTEXT: RefCell<Option<Handle>> = const { RefCell::new(None) }
+    static CONTEXT: RefCell<Option<Handle>> = const { RefCell::new(None) };
+
+    /// A borrowed, zero-allocation alternative to `CONTEXT`, installed for
+    /// the duration of a closure by [`scope`]. Consulted first by
+    /// `try_current` and the `try_*` driver and spawner accessors;
+    /// `clock` reads only `CONTEXT`.
+    static SCOPED_CONTEXT: Cell<*const Handle> = const { Cell::new(ptr::null()) };
+
+    /// A type-erased, application-defined context slot alongside `CONTEXT`.
+    /// Unrelated to tokio's own `Handle`, but installed and consulted
+    /// through the same [`context_ptr`]/[`set_context_ptr`] boundary, so a
+    /// plugin can share per-thread state with its host without inventing a
+    /// parallel FFI mechanism.
+    static APP_CONTEXT: RefCell<Option<Box<dyn Any + Send>>> = const { RefCell::new(None) };
 }
 
-static mut CONTEXT_PTR : &LocalKey<RefCell<Option<Handle>>> = &CONTEXT;
+/// Temporarily installs `handle` as the current [`Handle`] and runs `f`,
+/// without cloning `handle` or taking ownership of it.
+///
+/// This is a borrowed-reference alternative to [`enter`]/[`try_enter`] for
+/// hot paths, such as nested `block_on`/`enter` regions, that would
+/// otherwise pay for an `Arc` clone on every context install.
+pub(crate) fn scope<F, R>(handle: &Handle, f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    struct Guard(*const Handle);
+
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            SCOPED_CONTEXT.with(|cell| cell.set(self.0));
+        }
+    }
+
+    let old = SCOPED_CONTEXT.with(|cell| cell.replace(handle as *const Handle));
+    let _guard = Guard(old);
+
+    f()
+}
+
+/// Returns the handle installed by [`scope`], if any, borrowed for as long
+/// as the returned pointer is dereferenced only within the `scope` call that
+/// installed it.
+///
+/// Returns `None` both when no `scope` is active and when `SCOPED_CONTEXT`
+/// has already been destroyed (e.g. called from a `Drop` impl during thread
+/// shutdown) — callers fall back to `try_with!`/`with!` on `CONTEXT` in
+/// either case, which already turns the latter into a proper
+/// `TryCurrentError` instead of panicking.
+fn scoped_handle() -> Option<*const Handle> {
+    SCOPED_CONTEXT
+        .try_with(|cell| cell.get())
+        .ok()
+        .filter(|ptr| !ptr.is_null())
+}
+
+/// The host's `CONTEXT`/`APP_CONTEXT` slots, bundled so [`set_context_ptr`]
+/// can publish both behind a single [`compare_exchange`], installed by
+/// [`set_context_ptr`] and consulted by the `try_with!`/`try_with_app!`/
+/// `with!` macros in place of this library's own thread locals.
+///
+/// Bundling matters: if the two slots were published through separate
+/// atomics, a reader could observe the `CONTEXT` pointer installed but not
+/// yet the `APP_CONTEXT` one (or vice versa), and would silently fall back
+/// to its own local `APP_CONTEXT` in that window instead of erroring or
+/// waiting. A single pointer swap makes the registration atomic: either
+/// both slots are visible to a reader, or neither is.
+///
+/// [`compare_exchange`]: AtomicPtr::compare_exchange
+struct RegisteredContext {
+    ctx: *const LocalKey<RefCell<Option<Handle>>>,
+    app: *const LocalKey<RefCell<Option<Box<dyn Any + Send>>>>,
+}
+
+// SAFETY: the pointed-to `LocalKey`s are `'static` and never mutated through
+// this struct, only read.
+unsafe impl Send for RegisteredContext {}
+unsafe impl Sync for RegisteredContext {}
+
+/// This is a single registration slot, not a collection: only one loader's
+/// context can be active in a process at a time, since `try_with!`/`with!`
+/// have no way to pick among several. Multiple plugins binding to the
+/// *same* loader are fine — [`set_context_ptr`] treats re-registering an
+/// identical pointer as a no-op — but two different pointers racing to
+/// register is a misuse this type can't resolve on its own, so the second,
+/// conflicting call is rejected with [`SetContextError::Conflict`] rather
+/// than silently overwriting the first.
+///
+/// Once installed, the pointee is never freed or mutated for the remaining
+/// life of the process, so dereferencing a value read out of this atomic is
+/// always sound.
+static REGISTERED_CONTEXT: AtomicPtr<RegisteredContext> = AtomicPtr::new(ptr::null_mut());
+
+/// A fingerprint of this build of tokio's `Handle` layout.
+///
+/// Not a cryptographic hash: just cheap insurance that two builds of tokio
+/// linked into the same process (host + plugin) that disagree on `Handle`'s
+/// layout don't silently alias the same thread-local slot. Folds in the
+/// crate version alongside `size_of`/`align_of`, since two builds can
+/// easily agree on size and alignment (e.g. reordered same-size fields)
+/// while still disagreeing on layout.
+const fn abi_hash() -> u64 {
+    const fn fnv1a(s: &str) -> u64 {
+        let bytes = s.as_bytes();
+        let mut hash = 0xcbf2_9ce4_8422_2325_u64;
+        let mut i = 0;
+        while i < bytes.len() {
+            hash ^= bytes[i] as u64;
+            hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+            i += 1;
+        }
+        hash
+    }
 
-/// Get a ptr of the current TLS context (dynamic library use case).
-/// This opaque ptr must be use with the [`set_context_ptr`] in an init function of
-/// the dynamic library before it can use any tokio functionality.
+    (std::mem::size_of::<Handle>() as u64)
+        ^ ((std::mem::align_of::<Handle>() as u64) << 32)
+        ^ fnv1a(env!("CARGO_PKG_VERSION"))
+        ^ 0x746f_6b69_6f00_0001
+}
+
+/// An opaque, versioned handle to a process's TLS runtime context slot.
+///
+/// Obtained from [`context_ptr`] in the loader (the process that owns the
+/// "real" context) and passed to [`set_context_ptr`] in a dynamically
+/// loaded plugin, so the plugin's calls into tokio observe the loader's
+/// current [`Handle`] instead of (or in addition to) its own.
+#[repr(C)]
+pub struct ContextDescriptor {
+    abi_hash: u64,
+    ptr: *const LocalKey<RefCell<Option<Handle>>>,
+    app_ptr: *const LocalKey<RefCell<Option<Box<dyn Any + Send>>>>,
+}
+
+/// Returned by [`set_context_ptr`] when `descriptor` could not be
+/// installed.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SetContextError {
+    /// `descriptor` was produced by a build of tokio with an incompatible
+    /// `Handle` layout.
+    AbiMismatch,
+    /// A different loader context is already registered on this process.
+    ///
+    /// Only one loader's context can be active at a time; this plugin's
+    /// descriptor was not installed and the existing registration is
+    /// unchanged.
+    Conflict,
+}
+
+impl fmt::Display for SetContextError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SetContextError::AbiMismatch => {
+                fmt.write_str("context descriptor was built against an incompatible version of tokio")
+            }
+            SetContextError::Conflict => {
+                fmt.write_str("a different context is already registered on this process")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SetContextError {}
+
+/// Get an opaque descriptor for the current TLS context (dynamic library
+/// use case). This descriptor must be passed to [`set_context_ptr`] in an
+/// init function of the dynamic library before it can use any tokio
+/// functionality.
 #[allow(dead_code)]
-pub fn context_ptr() -> *const c_void
-{
-    &CONTEXT as *const LocalKey<RefCell<Option<Handle>>> as *const c_void
+pub fn context_ptr() -> ContextDescriptor {
+    ContextDescriptor {
+        abi_hash: abi_hash(),
+        ptr: &CONTEXT as *const LocalKey<RefCell<Option<Handle>>>,
+        app_ptr: &APP_CONTEXT as *const LocalKey<RefCell<Option<Box<dyn Any + Send>>>>,
+    }
 }
 
 /// Set the context of the main process (dynamic library use case).
-/// Must be called from the dynamically loaded library with the context ptr created with
-/// [`context_ptr`] from the main process (the loader)
+/// Must be called from the dynamically loaded library with the descriptor
+/// created with [`context_ptr`] from the main process (the loader).
+///
+/// Returns [`SetContextError::AbiMismatch`] without installing anything if
+/// `descriptor` was produced by an incompatible build of tokio.
+///
+/// Returns [`SetContextError::Conflict`] without installing anything if a
+/// *different* loader context has already been registered on this process.
+/// Calling this repeatedly with the same descriptor (e.g. from multiple
+/// plugins binding to the same loader) is fine and returns `Ok(())` each
+/// time.
 #[allow(dead_code)]
-pub fn set_context_ptr(ptr: *const c_void)
-{
-    unsafe { CONTEXT_PTR = &*(ptr as *const LocalKey<RefCell<Option<Handle>>>) };
+pub fn set_context_ptr(descriptor: ContextDescriptor) -> Result<(), SetContextError> {
+    if descriptor.abi_hash != abi_hash() {
+        return Err(SetContextError::AbiMismatch);
+    }
+
+    let registered = Box::into_raw(Box::new(RegisteredContext {
+        ctx: descriptor.ptr,
+        app: descriptor.app_ptr,
+    }));
+
+    match REGISTERED_CONTEXT.compare_exchange(
+        ptr::null_mut(),
+        registered,
+        Ordering::AcqRel,
+        Ordering::Acquire,
+    ) {
+        Ok(_) => Ok(()),
+        Err(existing) => {
+            // SAFETY: `existing` was installed by a previous successful call
+            // to this function, and is never freed or mutated afterward.
+            let existing = unsafe { &*existing };
+            let result = if existing.ctx == descriptor.ptr && existing.app == descriptor.app_ptr {
+                Ok(())
+            } else {
+                Err(SetContextError::Conflict)
+            };
+
+            // SAFETY: the CAS above failed, so `registered` was never
+            // published; this thread still has exclusive ownership of it.
+            drop(unsafe { Box::from_raw(registered) });
+
+            result
+        }
+    }
 }
 
 macro_rules! try_with
 {
-    ($f: expr) => { unsafe { CONTEXT_PTR.try_with($f) } }
+    ($f: expr) => {{
+        let registered = REGISTERED_CONTEXT.load(Ordering::Acquire);
+        if registered.is_null() {
+            CONTEXT.try_with($f)
+        } else {
+            // SAFETY: `registered` was installed by `set_context_ptr`, which
+            // only accepts descriptors whose ABI hash matches this build's,
+            // and it points at `'static` `LocalKey`s owned by the loader
+            // that outlives this plugin. The pointee is never freed or
+            // mutated once published.
+            unsafe { (*(*registered).ctx).try_with($f) }
+        }
+    }}
 }
 macro_rules! with
 {
-    ($f: expr) => { unsafe { CONTEXT_PTR.with($f) } }
+    ($f: expr) => {{
+        let registered = REGISTERED_CONTEXT.load(Ordering::Acquire);
+        if registered.is_null() {
+            CONTEXT.with($f)
+        } else {
+            // SAFETY: see `try_with!`.
+            unsafe { (*(*registered).ctx).with($f) }
+        }
+    }}
+}
+
+macro_rules! try_with_app
+{
+    ($f: expr) => {{
+        let registered = REGISTERED_CONTEXT.load(Ordering::Acquire);
+        if registered.is_null() {
+            APP_CONTEXT.try_with($f)
+        } else {
+            // SAFETY: see `try_with!`.
+            unsafe { (*(*registered).app).try_with($f) }
+        }
+    }}
+}
+
+/// Stores `value` in this thread's application-defined context slot,
+/// overwriting any previous value.
+///
+/// This slot is unrelated to tokio's own [`Handle`], but is transported
+/// across the same [`context_ptr`]/[`set_context_ptr`] boundary, so a
+/// plugin loaded via that handshake observes the same value as its host.
+///
+/// Does nothing if this thread's context slot has already been destroyed
+/// (e.g. called from a `Drop` impl during thread shutdown).
+pub fn set_app_context<T: Any + Send>(value: T) {
+    let _ = try_with_app!(|ctx| {
+        *ctx.borrow_mut() = Some(Box::new(value) as Box<dyn Any + Send>);
+    });
+}
+
+/// Runs `f` with a reference to this thread's application context value,
+/// if one is set and its type matches `T`.
+///
+/// Returns `None` without calling `f` if no value is set, the stored value
+/// isn't a `T`, or this thread's context slot has already been destroyed.
+pub fn with_app_context<T: Any + Send, F, R>(f: F) -> Option<R>
+where
+    F: FnOnce(&T) -> R,
+{
+    try_with_app!(|ctx| ctx.borrow().as_ref()?.downcast_ref::<T>().map(f))
+        .ok()
+        .flatten()
+}
+
+/// Takes the value out of this thread's application context slot, leaving
+/// it empty, if one is set and its type matches `T`.
+///
+/// Returns `None` if no value was set, the stored value isn't a `T` (in
+/// which case it is left in place), or this thread's context slot has
+/// already been destroyed.
+pub fn take_app_context<T: Any + Send>() -> Option<T> {
+    try_with_app!(|ctx| {
+        let mut slot = ctx.borrow_mut();
+        if matches!(&*slot, Some(value) if value.is::<T>()) {
+            slot.take()?.downcast::<T>().ok().map(|value| *value)
+        } else {
+            None
+        }
+    })
+    .ok()
+    .flatten()
 }
 
 
 pub(crate) fn try_current() -> Result<Handle, crate::runtime::TryCurrentError> {
+    if let Some(ptr) = scoped_handle() {
+        // SAFETY: `ptr` is only non-null for the duration of the `scope`
+        // call that installed it, and this access happens within that call.
+        return Ok(unsafe { (*ptr).clone() });
+    }
+
     match try_with!(|ctx| ctx.borrow().clone()) {
         Ok(Some(handle)) => Ok(handle),
         Ok(None) => Err(TryCurrentError::new_no_context()),
@@ -57,39 +350,75 @@ pub(crate) fn current() -> Handle {
 }
 
 cfg_io_driver! {
+    /// Like [`io_handle`], but returns an error instead of panicking when
+    /// there's no current context, or the thread-local context has already
+    /// been destroyed (e.g. when called from a `Drop` impl during shutdown).
+    pub(crate) fn try_io_handle() -> Result<crate::runtime::driver::IoHandle, TryCurrentError> {
+        if let Some(ptr) = scoped_handle() {
+            // SAFETY: see `try_current`.
+            return Ok(unsafe { (*ptr).as_inner().io_handle.clone() });
+        }
+
+        match try_with!(|ctx| ctx.borrow().as_ref().map(|ctx| ctx.as_inner().io_handle.clone())) {
+            Ok(Some(io_handle)) => Ok(io_handle),
+            Ok(None) => Err(TryCurrentError::new_no_context()),
+            Err(_access_error) => Err(TryCurrentError::new_thread_local_destroyed()),
+        }
+    }
+
     #[track_caller]
     pub(crate) fn io_handle() -> crate::runtime::driver::IoHandle {
-        match try_with!(|ctx| {
-            let ctx = ctx.borrow();
-            ctx.as_ref().expect(crate::util::error::CONTEXT_MISSING_ERROR).as_inner().io_handle.clone()
-        }) {
+        match try_io_handle() {
             Ok(io_handle) => io_handle,
-            Err(_) => panic!("{}", crate::util::error::THREAD_LOCAL_DESTROYED_ERROR),
+            Err(e) => panic!("{}", e),
         }
     }
 }
 
 cfg_signal_internal! {
+    #[cfg(unix)]
+    /// Like [`signal_handle`], but returns an error instead of panicking.
+    pub(crate) fn try_signal_handle() -> Result<crate::runtime::driver::SignalHandle, TryCurrentError> {
+        if let Some(ptr) = scoped_handle() {
+            // SAFETY: see `try_current`.
+            return Ok(unsafe { (*ptr).as_inner().signal_handle.clone() });
+        }
+
+        match try_with!(|ctx| ctx.borrow().as_ref().map(|ctx| ctx.as_inner().signal_handle.clone())) {
+            Ok(Some(signal_handle)) => Ok(signal_handle),
+            Ok(None) => Err(TryCurrentError::new_no_context()),
+            Err(_access_error) => Err(TryCurrentError::new_thread_local_destroyed()),
+        }
+    }
+
     #[cfg(unix)]
     pub(crate) fn signal_handle() -> crate::runtime::driver::SignalHandle {
-        match try_with!(|ctx| {
-            let ctx = ctx.borrow();
-            ctx.as_ref().expect(crate::util::error::CONTEXT_MISSING_ERROR).as_inner().signal_handle.clone()
-        }) {
+        match try_signal_handle() {
             Ok(signal_handle) => signal_handle,
-            Err(_) => panic!("{}", crate::util::error::THREAD_LOCAL_DESTROYED_ERROR),
+            Err(e) => panic!("{}", e),
         }
     }
 }
 
 cfg_time! {
+    /// Like [`time_handle`], but returns an error instead of panicking.
+    pub(crate) fn try_time_handle() -> Result<crate::runtime::driver::TimeHandle, TryCurrentError> {
+        if let Some(ptr) = scoped_handle() {
+            // SAFETY: see `try_current`.
+            return Ok(unsafe { (*ptr).as_inner().time_handle.clone() });
+        }
+
+        match try_with!(|ctx| ctx.borrow().as_ref().map(|ctx| ctx.as_inner().time_handle.clone())) {
+            Ok(Some(time_handle)) => Ok(time_handle),
+            Ok(None) => Err(TryCurrentError::new_no_context()),
+            Err(_access_error) => Err(TryCurrentError::new_thread_local_destroyed()),
+        }
+    }
+
     pub(crate) fn time_handle() -> crate::runtime::driver::TimeHandle {
-        match try_with!(|ctx| {
-            let ctx = ctx.borrow();
-            ctx.as_ref().expect(crate::util::error::CONTEXT_MISSING_ERROR).as_inner().time_handle.clone()
-        }) {
+        match try_time_handle() {
             Ok(time_handle) => time_handle,
-            Err(_) => panic!("{}", crate::util::error::THREAD_LOCAL_DESTROYED_ERROR),
+            Err(e) => panic!("{}", e),
         }
     }
 
@@ -104,10 +433,27 @@ cfg_time! {
 }
 
 cfg_rt! {
-    pub(crate) fn spawn_handle() -> Option<crate::runtime::Spawner> {
+    /// Like [`spawn_handle`], but returns an error instead of panicking.
+    pub(crate) fn try_spawn_handle() -> Result<crate::runtime::Spawner, TryCurrentError> {
+        if let Some(ptr) = scoped_handle() {
+            // SAFETY: see `try_current`.
+            return Ok(unsafe { (*ptr).spawner.clone() });
+        }
+
         match try_with!(|ctx| (*ctx.borrow()).as_ref().map(|ctx| ctx.spawner.clone())) {
-            Ok(spawner) => spawner,
-            Err(_) => panic!("{}", crate::util::error::THREAD_LOCAL_DESTROYED_ERROR),
+            Ok(Some(spawner)) => Ok(spawner),
+            Ok(None) => Err(TryCurrentError::new_no_context()),
+            Err(_access_error) => Err(TryCurrentError::new_thread_local_destroyed()),
+        }
+    }
+
+    pub(crate) fn spawn_handle() -> Option<crate::runtime::Spawner> {
+        match try_spawn_handle() {
+            Ok(spawner) => Some(spawner),
+            Err(e) if e.is_thread_local_destroyed() => {
+                panic!("{}", crate::util::error::THREAD_LOCAL_DESTROYED_ERROR)
+            }
+            Err(_) => None,
         }
     }
 }
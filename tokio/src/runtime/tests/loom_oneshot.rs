@@ -1,9 +1,13 @@
-use crate::loom::sync::{Arc, Mutex};
+use crate::loom::sync::{Arc, Condvar, Mutex};
 use loom::sync::Notify;
 
+use std::time::{Duration, Instant};
+
 pub(crate) fn channel<T>() -> (Sender<T>, Receiver<T>) {
     let inner = Arc::new(Inner {
         notify: Notify::new(),
+        timeout_wait: WaitCell::new(),
+        group: Mutex::new(None),
         value: Mutex::new(None),
     });
 
@@ -28,13 +32,66 @@ pub(crate) struct Receiver<T> {
 #[repr(C)]
 struct Inner<T> {
     notify: Notify,
+    /// A `Condvar`-backed wait cell for [`Receiver::recv_timeout`]. Kept
+    /// separate from `notify`: `loom::sync::Notify` has no timeout-aware
+    /// wait, whereas `Mutex`/`Condvar` are the primitives every loom-driven
+    /// timeout test in this crate is already built on, so `wait_timeout` is
+    /// known to behave under loom's model rather than merely compiling.
+    timeout_wait: WaitCell,
+    /// Shared wait cell registered by [`select`] while this receiver is part
+    /// of a group, so a `send` can wake up whichever receiver the caller is
+    /// actually waiting on.
+    group: Mutex<Option<Arc<WaitCell>>>,
     value: Mutex<Option<T>>,
 }
 
+/// A one-shot, level-triggered wake signal with a timeout-aware wait,
+/// built from `Mutex`/`Condvar` so it behaves identically under loom and
+/// under real threads.
+struct WaitCell {
+    ready: Mutex<bool>,
+    condvar: Condvar,
+}
+
+impl WaitCell {
+    fn new() -> Self {
+        WaitCell {
+            ready: Mutex::new(false),
+            condvar: Condvar::new(),
+        }
+    }
+
+    fn notify(&self) {
+        *self.ready.lock() = true;
+        self.condvar.notify_all();
+    }
+
+    fn wait(&self) {
+        let mut ready = self.ready.lock();
+        while !*ready {
+            ready = self.condvar.wait(ready).unwrap();
+        }
+    }
+
+    /// Waits until notified or `remaining` elapses, whichever comes first.
+    fn wait_timeout(&self, remaining: Duration) {
+        let ready = self.ready.lock();
+        if *ready {
+            return;
+        }
+        let _ = self.condvar.wait_timeout(ready, remaining).unwrap();
+    }
+}
+
 impl<T> Sender<T> {
     pub(crate) fn send(self, value: T) {
         *self.inner.value.lock() = Some(value);
         self.inner.notify.notify();
+        self.inner.timeout_wait.notify();
+
+        if let Some(group) = &*self.inner.group.lock() {
+            group.notify();
+        }
     }
 }
 
@@ -48,4 +105,185 @@ impl<T> Receiver<T> {
             self.inner.notify.wait();
         }
     }
+
+    /// Like [`recv`], but gives up and hands the receiver back if `timeout`
+    /// elapses before a value arrives.
+    ///
+    /// [`recv`]: Receiver::recv
+    pub(crate) fn recv_timeout(self, timeout: Duration) -> Result<T, Self> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            if let Some(v) = self.inner.value.lock().take() {
+                return Ok(v);
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                // One last check under the lock: a value may have landed
+                // between the check above and computing `remaining`.
+                return match self.inner.value.lock().take() {
+                    Some(v) => Ok(v),
+                    None => Err(self),
+                };
+            }
+
+            self.inner.timeout_wait.wait_timeout(remaining);
+        }
+    }
+}
+
+/// Waits on a group of receivers and returns the first one that becomes
+/// ready, along with its index in `receivers` and the remaining, still-
+/// pending receivers.
+///
+/// Returns `None` if `timeout` elapses before any receiver is ready, or if
+/// `receivers` is empty.
+pub(crate) fn select<T>(
+    mut receivers: Vec<Receiver<T>>,
+    timeout: Option<Duration>,
+) -> Option<(usize, T, Vec<Receiver<T>>)> {
+    if receivers.is_empty() {
+        // Nothing could ever notify the group below; waiting on it would
+        // hang forever instead of reporting "nothing became ready".
+        return None;
+    }
+
+    let group = Arc::new(WaitCell::new());
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+    // Arm the shared wait cell on every receiver *before* scanning so that a
+    // `send` racing with the scan below still wakes us up, rather than being
+    // lost between "we checked this receiver" and "we started waiting".
+    for rx in &receivers {
+        *rx.inner.group.lock() = Some(group.clone());
+    }
+
+    let result = loop {
+        if let Some(idx) = receivers
+            .iter()
+            .position(|rx| rx.inner.value.lock().is_some())
+        {
+            let value = receivers[idx].inner.value.lock().take().unwrap();
+            break Some((idx, value));
+        }
+
+        match deadline {
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    // A value may have arrived between the last scan and
+                    // here; give it one final look before giving up.
+                    match receivers
+                        .iter()
+                        .position(|rx| rx.inner.value.lock().is_some())
+                    {
+                        Some(idx) => {
+                            let value = receivers[idx].inner.value.lock().take().unwrap();
+                            break Some((idx, value));
+                        }
+                        None => break None,
+                    }
+                }
+
+                group.wait_timeout(remaining);
+            }
+            None => group.wait(),
+        }
+    };
+
+    for rx in &receivers {
+        *rx.inner.group.lock() = None;
+    }
+
+    result.map(|(idx, value)| {
+        receivers.remove(idx);
+        (idx, value, receivers)
+    })
+}
+
+#[cfg(all(test, loom))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recv_timeout_returns_value_when_sent_before_deadline() {
+        loom::model(|| {
+            let (tx, rx) = channel();
+            tx.send("hello");
+            assert_eq!(rx.recv_timeout(Duration::from_secs(1)).ok(), Some("hello"));
+        });
+    }
+
+    #[test]
+    fn recv_timeout_hands_receiver_back_when_nothing_arrives() {
+        loom::model(|| {
+            let (_tx, rx) = channel::<&'static str>();
+            assert!(rx.recv_timeout(Duration::from_millis(0)).is_err());
+        });
+    }
+
+    #[test]
+    fn select_returns_ready_receiver_and_the_rest() {
+        loom::model(|| {
+            let (tx1, rx1) = channel();
+            let (_tx2, rx2) = channel();
+            tx1.send("first");
+
+            let (idx, value, rest) = select(vec![rx1, rx2], None).unwrap();
+            assert_eq!(idx, 0);
+            assert_eq!(value, "first");
+            assert_eq!(rest.len(), 1);
+        });
+    }
+
+    #[test]
+    fn select_on_empty_group_returns_none_immediately() {
+        loom::model(|| {
+            let receivers: Vec<Receiver<()>> = Vec::new();
+            assert!(select(receivers, None).is_none());
+        });
+    }
+
+    #[test]
+    fn recv_timeout_concurrent_send_is_not_lost() {
+        loom::model(|| {
+            let (tx, rx) = channel();
+
+            let sender = loom::thread::spawn(move || {
+                tx.send("hello");
+            });
+
+            // `send` may land at any point relative to `recv_timeout`'s
+            // value check and its wait on `timeout_wait`; the timeout is
+            // long enough that the only way this returns `Err` is if the
+            // send was lost, not if it was merely slow.
+            assert_eq!(rx.recv_timeout(Duration::from_secs(1)).ok(), Some("hello"));
+
+            sender.join().unwrap();
+        });
+    }
+
+    #[test]
+    fn select_concurrent_send_is_not_lost() {
+        loom::model(|| {
+            let (tx1, rx1) = channel();
+            let (_tx2, rx2) = channel();
+
+            let sender = loom::thread::spawn(move || {
+                tx1.send("first");
+            });
+
+            // Exercises the "arm-before-scan" invariant: `select` must have
+            // registered `group` on every receiver before it starts scanning,
+            // so a `send` racing with that scan still wakes it up instead of
+            // being missed.
+            let (idx, value, rest) = select(vec![rx1, rx2], None).unwrap();
+            assert_eq!(idx, 0);
+            assert_eq!(value, "first");
+            assert_eq!(rest.len(), 1);
+
+            sender.join().unwrap();
+        });
+    }
 }